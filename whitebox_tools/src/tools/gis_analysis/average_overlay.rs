@@ -15,6 +15,77 @@ use raster::*;
 use std::io::{Error, ErrorKind};
 use structures::Array2D;
 use tools::WhiteboxTool;
+use tools::parameter::{ParameterSchema, ParameterType, ToolParameter};
+
+// Declares the flags this tool accepts. The help text and the argument parsing are both
+// derived from this schema rather than being maintained by hand.
+fn schema() -> ParameterSchema {
+    ParameterSchema::new()
+        .add(ToolParameter {
+            name: "inputs".to_string(),
+            flags: vec!["-i".to_string(), "--inputs".to_string()],
+            description: "Input raster files, separated by commas or semicolons.".to_string(),
+            parameter_type: ParameterType::List,
+            default: None,
+            optional: false,
+        })
+        .add(ToolParameter {
+            name: "output".to_string(),
+            flags: vec!["-o".to_string(), "--output".to_string()],
+            description: "Output raster file.".to_string(),
+            parameter_type: ParameterType::Path,
+            default: None,
+            optional: false,
+        })
+        .add(ToolParameter {
+            name: "align".to_string(),
+            flags: vec!["--align".to_string()],
+            description: "Resample mismatched inputs onto a common grid instead of aborting.".to_string(),
+            parameter_type: ParameterType::Boolean,
+            default: None,
+            optional: true,
+        })
+        .add(ToolParameter {
+            name: "base".to_string(),
+            flags: vec!["--base".to_string()],
+            description: "Reference grid for --align; defaults to the first input raster.".to_string(),
+            parameter_type: ParameterType::Path,
+            default: None,
+            optional: true,
+        })
+        .add(ToolParameter {
+            name: "method".to_string(),
+            flags: vec!["--method".to_string()],
+            description: "Resampling kernel used by --align; one of 'nn', 'bilinear', or 'cubic' (default is 'bilinear').".to_string(),
+            parameter_type: ParameterType::Enum(vec!["nn".to_string(), "bilinear".to_string(), "cubic".to_string()]),
+            default: Some("bilinear".to_string()),
+            optional: true,
+        })
+}
+
+// The resampling kernel used when inputs are aligned onto a common reference grid.
+#[derive(Clone, Copy, PartialEq)]
+enum ResampleMethod {
+    // Nearest neighbour; the right choice for categorical layers.
+    Nearest,
+    // Bilinear interpolation over the four surrounding cells.
+    Bilinear,
+    // Bicubic (Catmull-Rom) convolution over the surrounding 4x4 window.
+    Cubic,
+}
+
+impl ResampleMethod {
+    // Parses the value of the --method flag, returning an error for unrecognized names.
+    fn from_str(val: &str) -> Result<ResampleMethod, Error> {
+        match val.trim().to_lowercase().as_ref() {
+            "nn" | "nearest" | "nearest_neighbour" | "nearest_neighbor" => Ok(ResampleMethod::Nearest),
+            "bilinear" | "linear" => Ok(ResampleMethod::Bilinear),
+            "cubic" | "bicubic" => Ok(ResampleMethod::Cubic),
+            _ => Err(Error::new(ErrorKind::InvalidInput,
+                                "Unrecognized --method value. Valid options are nn, bilinear, and cubic.")),
+        }
+    }
+}
 
 pub struct AverageOverlay {
     name: String,
@@ -29,8 +100,7 @@ impl AverageOverlay {
         
         let description = "Calculates the average for each grid cell from a group of raster images.".to_string();
         
-        let mut parameters = "-i, --inputs     Input raster files, separated by commas or semicolons.\n".to_owned();
-        parameters.push_str("-o, --output     Output raster file.\n");
+        let parameters = schema().to_parameters_string();
         let sep: String = path::MAIN_SEPARATOR.to_string();
         let p = format!("{}", env::current_dir().unwrap().display());
         let e = format!("{}", env::current_exe().unwrap().display());
@@ -38,7 +108,7 @@ impl AverageOverlay {
         if e.contains(".exe") {
             short_exe += ".exe";
         }
-        let usage = format!(">>.*{} -r={} --wd='*path*to*data*' -i='image1.dep;image2.dep;image3.dep' -o=output.dep", short_exe, name).replace("*", &sep);
+        let usage = format!(">>.*{} -r={} --wd='*path*to*data*' -i='image1.dep;image2.dep;image3.dep' -o=output.dep --align --method=bilinear", short_exe, name).replace("*", &sep);
     
         AverageOverlay { name: name, description: description, parameters: parameters, example_usage: usage }
     }
@@ -62,36 +132,16 @@ impl WhiteboxTool for AverageOverlay {
     }
 
     fn run<'a>(&self, args: Vec<String>, working_directory: &'a str, verbose: bool) -> Result<(), Error> {
-        let mut input_files = String::new();
-        let mut output_file = String::new();
-        
         if args.len() == 0 {
             return Err(Error::new(ErrorKind::InvalidInput,
                                 "Tool run with no paramters. Please see help (-h) for parameter descriptions."));
         }
-        for i in 0..args.len() {
-            let mut arg = args[i].replace("\"", "");
-            arg = arg.replace("\'", "");
-            let cmd = arg.split("="); // in case an equals sign was used
-            let vec = cmd.collect::<Vec<&str>>();
-            let mut keyval = false;
-            if vec.len() > 1 {
-                keyval = true;
-            }
-            if vec[0].to_lowercase() == "-i" || vec[0].to_lowercase() == "--inputs" {
-                if keyval {
-                    input_files = vec[1].to_string();
-                } else {
-                    input_files = args[i+1].to_string();
-                }
-            } else if vec[0].to_lowercase() == "-o" || vec[0].to_lowercase() == "--output" {
-                if keyval {
-                    output_file = vec[1].to_string();
-                } else {
-                    output_file = args[i+1].to_string();
-                }
-            }
-        }
+        let params = schema().parse(&args)?;
+        let input_files = params.value_or_default("inputs");
+        let mut output_file = params.value_or_default("output");
+        let align = params.is_set("align");
+        let method = ResampleMethod::from_str(&params.value_or_default("method"))?;
+        let base_file = params.get("base");
 
         if verbose {
             println!("***************{}", "*".repeat(self.get_tool_name().len()));
@@ -129,9 +179,39 @@ impl WhiteboxTool for AverageOverlay {
         let mut rows = 0isize;
         let mut columns = 0isize;
         let mut out_nodata = f64::MIN;
-        let mut in_nodata: f64;
-        let mut z: f64;
+
+        // In --align mode the output grid is fixed up-front from a reference raster (an
+        // explicit --base, otherwise the first input) so that every input can be resampled
+        // into it. The reference's affine geotransform is what each output cell centre is
+        // mapped through to locate its position in each source raster.
+        let mut ref_west = 0f64;
+        let mut ref_north = 0f64;
+        let mut ref_res_x = 0f64;
+        let mut ref_res_y = 0f64;
         let mut read_first_file = false;
+        if align {
+            let mut ref_file = match base_file {
+                Some(f) => f,
+                None => vec[0].trim().to_owned(),
+            };
+            if !ref_file.contains(&sep) {
+                ref_file = format!("{}{}", working_directory, ref_file);
+            }
+            let reference = Raster::new(&ref_file, "r")?;
+            rows = reference.configs.rows as isize;
+            columns = reference.configs.columns as isize;
+            out_nodata = reference.configs.nodata;
+            ref_west = reference.configs.west;
+            ref_north = reference.configs.north;
+            ref_res_x = reference.configs.resolution_x;
+            ref_res_y = reference.configs.resolution_y;
+
+            // initialize the output file and n from the reference grid
+            output = Raster::initialize_using_file(&output_file, &reference);
+            n = Array2D::new(rows, columns, 0i16, i16::MIN)?;
+            read_first_file = true;
+        }
+
         let mut i = 1;
         for value in vec {
             if !value.trim().is_empty() {
@@ -142,41 +222,69 @@ impl WhiteboxTool for AverageOverlay {
                     input_file = format!("{}{}", working_directory, input_file);
                 }
                 let input = Raster::new(&input_file, "r")?;
-                in_nodata = input.configs.nodata;
                 if !read_first_file {
                     read_first_file = true;
                     rows = input.configs.rows as isize;
                     columns = input.configs.columns as isize;
-                    out_nodata = in_nodata;
+                    out_nodata = input.configs.nodata;
 
                     // initialize the output file and n
                     output = Raster::initialize_using_file(&output_file, &input);
                     n = Array2D::new(rows, columns, 0i16, i16::MIN)?;
                 }
-                // check to ensure that all inputs have the same rows and columns
-                if input.configs.rows as isize != rows || input.configs.columns as isize != columns {
-                    return Err(Error::new(ErrorKind::InvalidInput,
-                                "The input files must have the same number of rows and columns and spatial extent."));
-                }
 
-                for row in 0..rows {
-                    for col in 0..columns {
-                        z = input[(row, col)];
-                        if z != in_nodata {
-                            if output[(row, col)] != out_nodata {
-                                output.increment(row, col, z);
+                if align {
+                    // Resample this input onto the reference grid, mapping each output cell
+                    // centre back into the source raster's coordinate space and sampling
+                    // with the selected kernel (NoData propagates as a skipped cell).
+                    for row in 0..rows {
+                        for col in 0..columns {
+                            let x = ref_west + ref_res_x * (col as f64 + 0.5);
+                            let y = ref_north - ref_res_y * (row as f64 + 0.5);
+                            if let Some(val) = resample(&input, x, y, method) {
+                                if n[(row, col)] > 0i16 {
+                                    output.increment(row, col, val);
+                                } else {
+                                    output[(row, col)] = val;
+                                }
                                 n.increment(row, col, 1i16);
-                            } else {
-                                output[(row, col)] = z;
-                                n[(row, col)] = 1i16;
+                            }
+                        }
+                        if verbose {
+                            progress = (100.0_f64 * row as f64 / (rows - 1) as f64) as usize;
+                            if progress != old_progress {
+                                println!("Progress (loop {} of {}): {}%", i, num_files + 1, progress);
+                                old_progress = progress;
                             }
                         }
                     }
-                    if verbose {
-                        progress = (100.0_f64 * row as f64 / (rows - 1) as f64) as usize;
-                        if progress != old_progress {
-                            println!("Progress (loop {} of {}): {}%", i, num_files + 1, progress);
-                            old_progress = progress;
+                } else {
+                    // check to ensure that all inputs have the same rows and columns
+                    if input.configs.rows as isize != rows || input.configs.columns as isize != columns {
+                        return Err(Error::new(ErrorKind::InvalidInput,
+                                    "The input files must have the same number of rows and columns and spatial extent. Use --align to resample mismatched inputs onto a common grid."));
+                    }
+
+                    let in_nodata = input.configs.nodata;
+                    for row in 0..rows {
+                        for col in 0..columns {
+                            let z = input[(row, col)];
+                            if z != in_nodata {
+                                if output[(row, col)] != out_nodata {
+                                    output.increment(row, col, z);
+                                    n.increment(row, col, 1i16);
+                                } else {
+                                    output[(row, col)] = z;
+                                    n[(row, col)] = 1i16;
+                                }
+                            }
+                        }
+                        if verbose {
+                            progress = (100.0_f64 * row as f64 / (rows - 1) as f64) as usize;
+                            if progress != old_progress {
+                                println!("Progress (loop {} of {}): {}%", i, num_files + 1, progress);
+                                old_progress = progress;
+                            }
                         }
                     }
                 }
@@ -186,7 +294,7 @@ impl WhiteboxTool for AverageOverlay {
 
         for row in 0..rows {
             for col in 0..columns {
-                z = output[(row, col)];
+                let z = output[(row, col)];
                 if z != out_nodata {
                     if n[(row, col)] > 0i16 {
                         output[(row, col)] = z / n[(row, col)] as f64;
@@ -219,4 +327,68 @@ impl WhiteboxTool for AverageOverlay {
 
         Ok(())
     }
+}
+
+// Samples `input` at the geographic coordinate (x, y) with the chosen kernel, mapping the
+// point into the raster's grid through its affine geotransform. Returns `None` when the
+// point falls outside the raster or any contributing cell is NoData, so that NoData
+// propagates into the aligned output.
+fn resample(input: &Raster, x: f64, y: f64, method: ResampleMethod) -> Option<f64> {
+    let nodata = input.configs.nodata;
+    let in_rows = input.configs.rows as isize;
+    let in_cols = input.configs.columns as isize;
+    let col_f = (x - input.configs.west) / input.configs.resolution_x - 0.5;
+    let row_f = (input.configs.north - y) / input.configs.resolution_y - 0.5;
+
+    // Reads a single cell, treating out-of-bounds and NoData alike as absent.
+    let get = |row: isize, col: isize| -> Option<f64> {
+        if row < 0 || row >= in_rows || col < 0 || col >= in_cols {
+            return None;
+        }
+        let v = input[(row, col)];
+        if v == nodata { None } else { Some(v) }
+    };
+
+    match method {
+        ResampleMethod::Nearest => get(row_f.round() as isize, col_f.round() as isize),
+        ResampleMethod::Bilinear => {
+            let r0 = row_f.floor() as isize;
+            let c0 = col_f.floor() as isize;
+            let fx = col_f - col_f.floor();
+            let fy = row_f - row_f.floor();
+            let v00 = get(r0, c0)?;
+            let v01 = get(r0, c0 + 1)?;
+            let v10 = get(r0 + 1, c0)?;
+            let v11 = get(r0 + 1, c0 + 1)?;
+            let top = v00 + fx * (v01 - v00);
+            let bottom = v10 + fx * (v11 - v10);
+            Some(top + fy * (bottom - top))
+        }
+        ResampleMethod::Cubic => {
+            let r0 = row_f.floor() as isize;
+            let c0 = col_f.floor() as isize;
+            let fx = col_f - col_f.floor();
+            let fy = row_f - row_f.floor();
+            // Interpolate along each of the four surrounding rows, then between them.
+            let mut rows_interp = [0f64; 4];
+            for j in 0..4 {
+                let mut vals = [0f64; 4];
+                for k in 0..4 {
+                    vals[k] = get(r0 - 1 + j as isize, c0 - 1 + k as isize)?;
+                }
+                rows_interp[j] = cubic_interp(&vals, fx);
+            }
+            Some(cubic_interp(&rows_interp, fy))
+        }
+    }
+}
+
+// One-dimensional Catmull-Rom cubic convolution across four samples `v`, at fractional
+// position `t` in [0, 1] between v[1] and v[2].
+fn cubic_interp(v: &[f64; 4], t: f64) -> f64 {
+    let a = -0.5 * v[0] + 1.5 * v[1] - 1.5 * v[2] + 0.5 * v[3];
+    let b = v[0] - 2.5 * v[1] + 2.0 * v[2] - 0.5 * v[3];
+    let c = -0.5 * v[0] + 0.5 * v[2];
+    let d = v[1];
+    ((a * t + b) * t + c) * t + d
 }
\ No newline at end of file
@@ -0,0 +1,9 @@
+// private sub-module defined in other files
+mod average_overlay;
+mod percentile_overlay;
+mod stat_overlay;
+
+// exports identifiers from private sub-modules in the current module namespace
+pub use self::average_overlay::AverageOverlay;
+pub use self::percentile_overlay::PercentileOverlay;
+pub use self::stat_overlay::StatOverlay;
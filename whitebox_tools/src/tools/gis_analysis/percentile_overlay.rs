@@ -0,0 +1,429 @@
+/*
+This tool is part of the WhiteboxTools geospatial analysis library.
+Authors: Dr. John Lindsay
+Created: July 18 2017
+Last Modified: July 18, 2017
+License: MIT
+*/
+extern crate time;
+
+use std::env;
+use std::path;
+use std::i16;
+use std::f64;
+use raster::*;
+use std::io::{Error, ErrorKind};
+use structures::Array2D;
+use tools::WhiteboxTool;
+use tools::parameter::{ParameterSchema, ParameterType, ToolParameter};
+
+// Declares the flags this tool accepts. The help text and the argument parsing are both
+// derived from this schema rather than being maintained by hand.
+fn schema() -> ParameterSchema {
+    ParameterSchema::new()
+        .add(ToolParameter {
+            name: "inputs".to_string(),
+            flags: vec!["-i".to_string(), "--inputs".to_string()],
+            description: "Input raster files, separated by commas or semicolons.".to_string(),
+            parameter_type: ParameterType::List,
+            default: None,
+            optional: false,
+        })
+        .add(ToolParameter {
+            name: "output".to_string(),
+            flags: vec!["-o".to_string(), "--output".to_string()],
+            description: "Output raster file.".to_string(),
+            parameter_type: ParameterType::Path,
+            default: None,
+            optional: false,
+        })
+        .add(ToolParameter {
+            name: "percentile".to_string(),
+            flags: vec!["--percentile".to_string()],
+            description: "Percentile to estimate, between 0 and 100 (default is 50, the median).".to_string(),
+            parameter_type: ParameterType::Float,
+            default: Some("50".to_string()),
+            optional: true,
+        })
+}
+
+pub struct PercentileOverlay {
+    name: String,
+    description: String,
+    parameters: String,
+    example_usage: String,
+}
+
+impl PercentileOverlay {
+    pub fn new() -> PercentileOverlay { // public constructor
+        let name = "PercentileOverlay".to_string();
+
+        let description = "Estimates a percentile (e.g. the median) for each grid cell across a group of raster images using constant per-cell memory.".to_string();
+
+        let parameters = schema().to_parameters_string();
+        let sep: String = path::MAIN_SEPARATOR.to_string();
+        let p = format!("{}", env::current_dir().unwrap().display());
+        let e = format!("{}", env::current_exe().unwrap().display());
+        let mut short_exe = e.replace(&p, "").replace(".exe", "").replace(".", "").replace(&sep, "");
+        if e.contains(".exe") {
+            short_exe += ".exe";
+        }
+        let usage = format!(">>.*{} -r={} --wd='*path*to*data*' -i='image1.dep;image2.dep;image3.dep' -o=output.dep --percentile=50", short_exe, name).replace("*", &sep);
+
+        PercentileOverlay { name: name, description: description, parameters: parameters, example_usage: usage }
+    }
+}
+
+impl WhiteboxTool for PercentileOverlay {
+    fn get_tool_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_tool_description(&self) -> String {
+        self.description.clone()
+    }
+
+    fn get_tool_parameters(&self) -> String {
+        self.parameters.clone()
+    }
+
+    fn get_example_usage(&self) -> String {
+        self.example_usage.clone()
+    }
+
+    fn run<'a>(&self, args: Vec<String>, working_directory: &'a str, verbose: bool) -> Result<(), Error> {
+        if args.len() == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                "Tool run with no paramters. Please see help (-h) for parameter descriptions."));
+        }
+        let params = schema().parse(&args)?;
+        let mut output_file = params.value_or_default("output");
+        let percentile = params.get_float("percentile").unwrap_or(50f64);
+
+        if percentile < 0f64 || percentile > 100f64 {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                "The --percentile value must be between 0 and 100."));
+        }
+        let p = percentile / 100f64; // desired quantile in [0, 1]
+
+        if verbose {
+            println!("***************{}", "*".repeat(self.get_tool_name().len()));
+            println!("* Welcome to {} *", self.get_tool_name());
+            println!("***************{}", "*".repeat(self.get_tool_name().len()));
+        }
+
+        let sep: String = path::MAIN_SEPARATOR.to_string();
+
+        let mut progress: usize;
+        let mut old_progress: usize = 1;
+
+        if !output_file.contains(&sep) {
+            output_file = format!("{}{}", working_directory, output_file);
+        }
+
+        let vec = params.get_list("inputs");
+        let num_files = vec.len();
+        if num_files < 2 {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                "There is something incorrect about the input files. At least two inputs are required to operate this tool."));
+        }
+
+        let start = time::now();
+
+        // We need to initialize output and the marker buffers here, but in reality this
+        // can't be done until we know the size of rows and columns, which occurs during
+        // the first loop.
+        let mut output: Raster = Raster::new(&output_file, "w")?;
+        let mut n: Array2D<i16> = Array2D::new(0, 0, 0i16, i16::MIN)?; // count of valid inputs per cell
+
+        // The five P-square markers per cell are stored in parallel buffers: q1..q5 are
+        // the marker heights, np1..np5 their integer positions, and dp1..dp5 their desired
+        // (fractional) positions. A cell with fewer than five observations buffers its
+        // heights in q1..q5 (kept sorted) and is resolved by exact interpolation at the end.
+        let mut q1: Array2D<f64> = Array2D::new(0, 0, 0f64, f64::MIN)?;
+        let mut q2: Array2D<f64> = Array2D::new(0, 0, 0f64, f64::MIN)?;
+        let mut q3: Array2D<f64> = Array2D::new(0, 0, 0f64, f64::MIN)?;
+        let mut q4: Array2D<f64> = Array2D::new(0, 0, 0f64, f64::MIN)?;
+        let mut q5: Array2D<f64> = Array2D::new(0, 0, 0f64, f64::MIN)?;
+        let mut np: Vec<[f64; 5]> = vec![];
+        let mut dp: Vec<[f64; 5]> = vec![];
+        // Fixed desired-position increments for the chosen quantile.
+        let d = [0f64, p / 2f64, p, (1f64 + p) / 2f64, 1f64];
+
+        let mut rows = 0isize;
+        let mut columns = 0isize;
+        let mut out_nodata = f64::MIN;
+        let mut in_nodata: f64;
+        let mut z: f64;
+        let mut read_first_file = false;
+        let mut i = 1;
+        for value in vec {
+            if !value.trim().is_empty() {
+                if verbose { println!("Reading data...") };
+
+                let mut input_file = value.trim().to_owned();
+                if !input_file.contains(&sep) {
+                    input_file = format!("{}{}", working_directory, input_file);
+                }
+                let input = Raster::new(&input_file, "r")?;
+                in_nodata = input.configs.nodata;
+                if !read_first_file {
+                    read_first_file = true;
+                    rows = input.configs.rows as isize;
+                    columns = input.configs.columns as isize;
+                    out_nodata = in_nodata;
+
+                    // initialize the output file and the marker buffers
+                    output = Raster::initialize_using_file(&output_file, &input);
+                    n = Array2D::new(rows, columns, 0i16, i16::MIN)?;
+                    q1 = Array2D::new(rows, columns, 0f64, f64::MIN)?;
+                    q2 = Array2D::new(rows, columns, 0f64, f64::MIN)?;
+                    q3 = Array2D::new(rows, columns, 0f64, f64::MIN)?;
+                    q4 = Array2D::new(rows, columns, 0f64, f64::MIN)?;
+                    q5 = Array2D::new(rows, columns, 0f64, f64::MIN)?;
+                    np = vec![[1f64, 2f64, 3f64, 4f64, 5f64]; (rows * columns) as usize];
+                    dp = vec![[1f64, 1f64 + 2f64 * p, 1f64 + 4f64 * p, 3f64 + 2f64 * p, 5f64]; (rows * columns) as usize];
+                }
+                // check to ensure that all inputs have the same rows and columns
+                if input.configs.rows as isize != rows || input.configs.columns as isize != columns {
+                    return Err(Error::new(ErrorKind::InvalidInput,
+                                "The input files must have the same number of rows and columns and spatial extent."));
+                }
+
+                for row in 0..rows {
+                    for col in 0..columns {
+                        z = input[(row, col)];
+                        if z != in_nodata {
+                            let count = n[(row, col)];
+                            let idx = (row * columns + col) as usize;
+                            if count < 5i16 {
+                                // Seed the marker heights from the first five observations,
+                                // inserting into the already-sorted prefix.
+                                let mut q = [q1[(row, col)], q2[(row, col)], q3[(row, col)],
+                                             q4[(row, col)], q5[(row, col)]];
+                                let mut k = count as usize;
+                                while k > 0 && q[k - 1] > z {
+                                    q[k] = q[k - 1];
+                                    k -= 1;
+                                }
+                                q[k] = z;
+                                q1[(row, col)] = q[0];
+                                q2[(row, col)] = q[1];
+                                q3[(row, col)] = q[2];
+                                q4[(row, col)] = q[3];
+                                q5[(row, col)] = q[4];
+                            } else {
+                                let mut q = [q1[(row, col)], q2[(row, col)], q3[(row, col)],
+                                             q4[(row, col)], q5[(row, col)]];
+                                update_markers(&mut q, &mut np[idx], &mut dp[idx], &d, z);
+                                q1[(row, col)] = q[0];
+                                q2[(row, col)] = q[1];
+                                q3[(row, col)] = q[2];
+                                q4[(row, col)] = q[3];
+                                q5[(row, col)] = q[4];
+                            }
+                            n.increment(row, col, 1i16);
+                        }
+                    }
+                    if verbose {
+                        progress = (100.0_f64 * row as f64 / (rows - 1) as f64) as usize;
+                        if progress != old_progress {
+                            println!("Progress (loop {} of {}): {}%", i, num_files + 1, progress);
+                            old_progress = progress;
+                        }
+                    }
+                }
+            }
+            i += 1;
+        }
+
+        for row in 0..rows {
+            for col in 0..columns {
+                let count = n[(row, col)];
+                if count == 0i16 {
+                    output[(row, col)] = out_nodata;
+                } else if count < 5i16 {
+                    // Too few observations for the P-square markers: interpolate the quantile
+                    // directly from the sorted buffer.
+                    let q = [q1[(row, col)], q2[(row, col)], q3[(row, col)],
+                             q4[(row, col)], q5[(row, col)]];
+                    let rank = p * (count as f64 - 1f64);
+                    let lo = rank.floor() as usize;
+                    let hi = rank.ceil() as usize;
+                    output[(row, col)] = q[lo] + (rank - lo as f64) * (q[hi] - q[lo]);
+                } else {
+                    // The middle marker q3 tracks the requested quantile.
+                    output[(row, col)] = q3[(row, col)];
+                }
+            }
+            if verbose {
+                progress = (100.0_f64 * row as f64 / (rows - 1) as f64) as usize;
+                if progress != old_progress {
+                    println!("Progress (loop {} of {}): {}%", num_files + 1, num_files + 1, progress);
+                    old_progress = progress;
+                }
+            }
+        }
+
+        let end = time::now();
+        let elapsed_time = end - start;
+        output.add_metadata_entry(format!("Created by whitebox_tools\' {} tool", self.get_tool_name()));
+        output.add_metadata_entry(format!("Elapsed Time (including I/O): {}", elapsed_time).replace("PT", ""));
+
+        if verbose { println!("Saving data...") };
+        let _ = match output.write() {
+            Ok(_) => if verbose { println!("Output file written") },
+            Err(e) => return Err(e),
+        };
+
+        println!("{}", &format!("Elapsed Time (including I/O): {}", elapsed_time).replace("PT", ""));
+
+        Ok(())
+    }
+}
+
+// Incorporates a single observation into a cell's five P-square markers, following
+// Jain & Chlamtac's Piecewise-Parabolic (P-square) algorithm. `q` are the marker
+// heights, `np` the integer marker positions, `dp` the desired positions, and `d`
+// the fixed desired-position increments for the target quantile.
+fn update_markers(q: &mut [f64; 5], np: &mut [f64; 5], dp: &mut [f64; 5], d: &[f64; 5], x: f64) {
+    // Locate the cell interval k such that q[k] <= x < q[k+1], extending the outer
+    // markers when the observation falls beyond the current range.
+    let k: usize;
+    if x < q[0] {
+        q[0] = x;
+        k = 0;
+    } else if x >= q[4] {
+        q[4] = x;
+        k = 3;
+    } else {
+        let mut j = 0;
+        while j < 4 && !(q[j] <= x && x < q[j + 1]) {
+            j += 1;
+        }
+        k = if j > 3 { 3 } else { j };
+    }
+
+    // Shift the integer positions of the markers above the interval, and advance every
+    // desired position by its increment.
+    for i in (k + 1)..5 {
+        np[i] += 1f64;
+    }
+    for i in 0..5 {
+        dp[i] += d[i];
+    }
+
+    // Adjust the three interior markers toward their desired positions.
+    for i in 1..4 {
+        let delta = dp[i] - np[i];
+        if (delta >= 1f64 && np[i + 1] - np[i] > 1f64) || (delta <= -1f64 && np[i - 1] - np[i] < -1f64) {
+            let dir = if delta >= 0f64 { 1f64 } else { -1f64 };
+            let parabolic = parabolic(q, np, i, dir);
+            if q[i - 1] < parabolic && parabolic < q[i + 1] {
+                q[i] = parabolic;
+            } else {
+                // The parabolic prediction broke the monotonic ordering; fall back to
+                // the linear formula.
+                let ii = (i as isize + dir as isize) as usize;
+                q[i] += dir * (q[ii] - q[i]) / (np[ii] - np[i]);
+            }
+            np[i] += dir;
+        }
+    }
+}
+
+// The piecewise-parabolic prediction for marker `i` moving by `dir` (+1 or -1).
+fn parabolic(q: &[f64; 5], np: &[f64; 5], i: usize, dir: f64) -> f64 {
+    let lo = i - 1;
+    let hi = i + 1;
+    q[i] + dir / (np[hi] - np[lo]) *
+        ((np[i] - np[lo] + dir) * (q[hi] - q[i]) / (np[hi] - np[i]) +
+         (np[hi] - np[i] - dir) * (q[i] - q[lo]) / (np[i] - np[lo]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Drives a single cell's five markers over `vals` exactly as `run` does, returning the
+    // estimated quantile for probability `p`. This mirrors the production per-cell logic,
+    // including the fewer-than-five-observations exact fallback and the empty-cell case
+    // (which `run` writes out as NoData).
+    fn estimate(vals: &[f64], p: f64) -> f64 {
+        let d = [0f64, p / 2f64, p, (1f64 + p) / 2f64, 1f64];
+        let mut q = [0f64; 5];
+        let mut np = [1f64, 2f64, 3f64, 4f64, 5f64];
+        let mut dp = [1f64, 1f64 + 2f64 * p, 1f64 + 4f64 * p, 3f64 + 2f64 * p, 5f64];
+        let mut count = 0usize;
+        for &x in vals {
+            if count < 5 {
+                let mut k = count;
+                while k > 0 && q[k - 1] > x {
+                    q[k] = q[k - 1];
+                    k -= 1;
+                }
+                q[k] = x;
+            } else {
+                update_markers(&mut q, &mut np, &mut dp, &d, x);
+            }
+            count += 1;
+        }
+        if count == 0 {
+            return f64::NAN;
+        }
+        if count < 5 {
+            let rank = p * (count as f64 - 1f64);
+            let lo = rank.floor() as usize;
+            let hi = rank.ceil() as usize;
+            return q[lo] + (rank - lo as f64) * (q[hi] - q[lo]);
+        }
+        q[2]
+    }
+
+    // The exact quantile by sorting, using the same rank convention as the fallback path.
+    fn exact(vals: &[f64], p: f64) -> f64 {
+        let mut v = vals.to_vec();
+        v.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let rank = p * (v.len() as f64 - 1f64);
+        let lo = rank.floor() as usize;
+        let hi = rank.ceil() as usize;
+        v[lo] + (rank - lo as f64) * (v[hi] - v[lo])
+    }
+
+    #[test]
+    fn fewer_than_five_is_exact() {
+        // With under five observations the markers are resolved by exact interpolation.
+        assert_eq!(estimate(&[30f64, 10f64, 20f64], 0.5), 20f64);
+        let four = [40f64, 10f64, 30f64, 20f64];
+        assert_eq!(estimate(&four, 0.5), exact(&four, 0.5));
+    }
+
+    #[test]
+    fn empty_stack_yields_no_value() {
+        // A cell with no valid inputs has no estimate; `run` emits NoData for it.
+        assert!(estimate(&[], 0.5).is_nan());
+    }
+
+    #[test]
+    fn median_tracks_exact_on_ramp() {
+        let vals: Vec<f64> = (0..101).map(|i| i as f64).collect();
+        let est = estimate(&vals, 0.5);
+        assert!((est - exact(&vals, 0.5)).abs() < 2f64,
+                "median estimate {} too far from exact {}", est, exact(&vals, 0.5));
+    }
+
+    #[test]
+    fn upper_percentile_tracks_exact() {
+        // A deterministic, unsorted stack that exercises the interval search and the
+        // parabolic marker adjustment.
+        let mut vals = vec![];
+        let mut x = 3f64;
+        for _ in 0..200 {
+            x = (x * 1.1 + 7f64) % 100f64;
+            vals.push(x);
+        }
+        let est = estimate(&vals, 0.9);
+        assert!((est - exact(&vals, 0.9)).abs() < 5f64,
+                "p90 estimate {} too far from exact {}", est, exact(&vals, 0.9));
+    }
+}
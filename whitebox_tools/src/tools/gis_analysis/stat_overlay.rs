@@ -0,0 +1,369 @@
+/*
+This tool is part of the WhiteboxTools geospatial analysis library.
+Authors: Dr. John Lindsay
+Created: July 14 2017
+Last Modified: July 14, 2017
+License: MIT
+*/
+extern crate time;
+
+use std::collections::HashMap;
+use std::env;
+use std::path;
+use std::i16;
+use std::f64;
+use raster::*;
+use std::io::{Error, ErrorKind};
+use structures::Array2D;
+use tools::WhiteboxTool;
+use tools::parameter::{ParameterSchema, ParameterType, ToolParameter};
+
+// Declares the flags this tool accepts. The help text and the argument parsing are both
+// derived from this schema rather than being maintained by hand.
+fn schema() -> ParameterSchema {
+    ParameterSchema::new()
+        .add(ToolParameter {
+            name: "inputs".to_string(),
+            flags: vec!["-i".to_string(), "--inputs".to_string()],
+            description: "Input raster files, separated by commas or semicolons.".to_string(),
+            parameter_type: ParameterType::List,
+            default: None,
+            optional: false,
+        })
+        .add(ToolParameter {
+            name: "output".to_string(),
+            flags: vec!["-o".to_string(), "--output".to_string()],
+            description: "Output raster file.".to_string(),
+            parameter_type: ParameterType::Path,
+            default: None,
+            optional: false,
+        })
+        .add(ToolParameter {
+            name: "stat".to_string(),
+            flags: vec!["--stat".to_string()],
+            description: "Statistic to calculate; one of 'mean', 'min', 'max', 'sum', 'range', 'stddev', 'variance', 'majority', or 'minority' (default is 'mean').".to_string(),
+            parameter_type: ParameterType::Enum(vec!["mean".to_string(), "average".to_string(), "avg".to_string(),
+                                                     "min".to_string(), "minimum".to_string(), "max".to_string(),
+                                                     "maximum".to_string(), "sum".to_string(), "total".to_string(),
+                                                     "range".to_string(), "stddev".to_string(), "std".to_string(),
+                                                     "standard_deviation".to_string(), "variance".to_string(),
+                                                     "var".to_string(), "majority".to_string(), "mode".to_string(),
+                                                     "minority".to_string()]),
+            default: Some("mean".to_string()),
+            optional: true,
+        })
+}
+
+// The cell-wise reduction applied across the input raster stack.
+#[derive(Clone, Copy, PartialEq)]
+enum Stat {
+    Mean,
+    Min,
+    Max,
+    Sum,
+    Range,
+    StdDev,
+    Variance,
+    Majority,
+    Minority,
+}
+
+impl Stat {
+    // Parses the value of the --stat flag, returning an error for unrecognized names.
+    fn from_str(val: &str) -> Result<Stat, Error> {
+        match val.trim().to_lowercase().as_ref() {
+            "mean" | "average" | "avg" => Ok(Stat::Mean),
+            "min" | "minimum" => Ok(Stat::Min),
+            "max" | "maximum" => Ok(Stat::Max),
+            "sum" | "total" => Ok(Stat::Sum),
+            "range" => Ok(Stat::Range),
+            "stddev" | "std" | "standard_deviation" => Ok(Stat::StdDev),
+            "variance" | "var" => Ok(Stat::Variance),
+            "majority" | "mode" => Ok(Stat::Majority),
+            "minority" => Ok(Stat::Minority),
+            _ => Err(Error::new(ErrorKind::InvalidInput,
+                                "Unrecognized --stat value. Valid options are mean, min, max, sum, range, stddev, variance, majority, and minority.")),
+        }
+    }
+}
+
+pub struct StatOverlay {
+    name: String,
+    description: String,
+    parameters: String,
+    example_usage: String,
+}
+
+impl StatOverlay {
+    pub fn new() -> StatOverlay { // public constructor
+        let name = "StatOverlay".to_string();
+
+        let description = "Calculates a cell-wise summary statistic (mean, min, max, sum, range, stddev, variance, majority, or minority) from a group of raster images.".to_string();
+
+        let parameters = schema().to_parameters_string();
+        let sep: String = path::MAIN_SEPARATOR.to_string();
+        let p = format!("{}", env::current_dir().unwrap().display());
+        let e = format!("{}", env::current_exe().unwrap().display());
+        let mut short_exe = e.replace(&p, "").replace(".exe", "").replace(".", "").replace(&sep, "");
+        if e.contains(".exe") {
+            short_exe += ".exe";
+        }
+        let usage = format!(">>.*{} -r={} --wd='*path*to*data*' -i='image1.dep;image2.dep;image3.dep' -o=output.dep --stat=range", short_exe, name).replace("*", &sep);
+
+        StatOverlay { name: name, description: description, parameters: parameters, example_usage: usage }
+    }
+}
+
+impl WhiteboxTool for StatOverlay {
+    fn get_tool_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_tool_description(&self) -> String {
+        self.description.clone()
+    }
+
+    fn get_tool_parameters(&self) -> String {
+        self.parameters.clone()
+    }
+
+    fn get_example_usage(&self) -> String {
+        self.example_usage.clone()
+    }
+
+    fn run<'a>(&self, args: Vec<String>, working_directory: &'a str, verbose: bool) -> Result<(), Error> {
+        if args.len() == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                "Tool run with no paramters. Please see help (-h) for parameter descriptions."));
+        }
+        let params = schema().parse(&args)?;
+        let mut output_file = params.value_or_default("output");
+        let stat = Stat::from_str(&params.value_or_default("stat"))?;
+
+        if verbose {
+            println!("***************{}", "*".repeat(self.get_tool_name().len()));
+            println!("* Welcome to {} *", self.get_tool_name());
+            println!("***************{}", "*".repeat(self.get_tool_name().len()));
+        }
+
+        let sep: String = path::MAIN_SEPARATOR.to_string();
+
+        let mut progress: usize;
+        let mut old_progress: usize = 1;
+
+        if !output_file.contains(&sep) {
+            output_file = format!("{}{}", working_directory, output_file);
+        }
+
+        let vec = params.get_list("inputs");
+        let num_files = vec.len();
+        if num_files < 2 {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                "There is something incorrect about the input files. At least two inputs are required to operate this tool."));
+        }
+
+        let start = time::now();
+
+        // We need to initialize output and n here, but in reality this can't be done
+        // until we know the size of rows and columns, which occurs during the first loop.
+        let mut output: Raster = Raster::new(&output_file, "w")?;
+        let mut n: Array2D<i16> = Array2D::new(0, 0, 0i16, i16::MIN)?; // use i16::MIN as the nodata value
+        // Running moments (Welford) for mean/variance/stddev and an auxiliary
+        // extreme buffer for range; both are only touched when the chosen
+        // statistic requires them.
+        let mut mean: Array2D<f64> = Array2D::new(0, 0, 0f64, f64::MIN)?;
+        let mut m2: Array2D<f64> = Array2D::new(0, 0, 0f64, f64::MIN)?;
+        let mut aux: Array2D<f64> = Array2D::new(0, 0, 0f64, f64::MIN)?;
+        // Observed-value counts per cell, only allocated for majority/minority.
+        let track_moments = stat == Stat::Mean || stat == Stat::Variance || stat == Stat::StdDev;
+        let track_aux = stat == Stat::Range;
+        let track_counts = stat == Stat::Majority || stat == Stat::Minority;
+        let mut counts: Vec<HashMap<i64, u32>> = vec![];
+        let mut rows = 0isize;
+        let mut columns = 0isize;
+        let mut out_nodata = f64::MIN;
+        let mut in_nodata: f64;
+        let mut z: f64;
+        let mut read_first_file = false;
+        let mut i = 1;
+        for value in vec {
+            if !value.trim().is_empty() {
+                if verbose { println!("Reading data...") };
+
+                let mut input_file = value.trim().to_owned();
+                if !input_file.contains(&sep) {
+                    input_file = format!("{}{}", working_directory, input_file);
+                }
+                let input = Raster::new(&input_file, "r")?;
+                in_nodata = input.configs.nodata;
+                if !read_first_file {
+                    read_first_file = true;
+                    rows = input.configs.rows as isize;
+                    columns = input.configs.columns as isize;
+                    out_nodata = in_nodata;
+
+                    // initialize the output file and the accumulators
+                    output = Raster::initialize_using_file(&output_file, &input);
+                    n = Array2D::new(rows, columns, 0i16, i16::MIN)?;
+                    if track_moments {
+                        mean = Array2D::new(rows, columns, 0f64, f64::MIN)?;
+                        m2 = Array2D::new(rows, columns, 0f64, f64::MIN)?;
+                    }
+                    if track_aux {
+                        aux = Array2D::new(rows, columns, 0f64, f64::MIN)?;
+                    }
+                    if track_counts {
+                        counts = vec![HashMap::new(); (rows * columns) as usize];
+                    }
+                }
+                // check to ensure that all inputs have the same rows and columns
+                if input.configs.rows as isize != rows || input.configs.columns as isize != columns {
+                    return Err(Error::new(ErrorKind::InvalidInput,
+                                "The input files must have the same number of rows and columns and spatial extent."));
+                }
+
+                for row in 0..rows {
+                    for col in 0..columns {
+                        z = input[(row, col)];
+                        if z != in_nodata {
+                            let first = n[(row, col)] == 0i16;
+                            n.increment(row, col, 1i16);
+                            match stat {
+                                Stat::Mean | Stat::Variance | Stat::StdDev => {
+                                    // Welford's online update of the running mean and sum of
+                                    // squared deviations.
+                                    let count = n[(row, col)] as f64;
+                                    let delta = z - mean[(row, col)];
+                                    mean.increment(row, col, delta / count);
+                                    let delta2 = z - mean[(row, col)];
+                                    m2.increment(row, col, delta * delta2);
+                                }
+                                Stat::Sum => {
+                                    // output is initialized to NoData, not 0, so the first
+                                    // valid observation must be assigned rather than added.
+                                    if first {
+                                        output[(row, col)] = z;
+                                    } else {
+                                        output.increment(row, col, z);
+                                    }
+                                }
+                                Stat::Min => {
+                                    if first || z < output[(row, col)] {
+                                        output[(row, col)] = z;
+                                    }
+                                }
+                                Stat::Max => {
+                                    if first || z > output[(row, col)] {
+                                        output[(row, col)] = z;
+                                    }
+                                }
+                                Stat::Range => {
+                                    // output holds the running minimum, aux the running maximum.
+                                    if first || z < output[(row, col)] {
+                                        output[(row, col)] = z;
+                                    }
+                                    if first || z > aux[(row, col)] {
+                                        aux[(row, col)] = z;
+                                    }
+                                }
+                                Stat::Majority | Stat::Minority => {
+                                    let idx = (row * columns + col) as usize;
+                                    *counts[idx].entry(z.to_bits() as i64).or_insert(0) += 1;
+                                }
+                            }
+                        }
+                    }
+                    if verbose {
+                        progress = (100.0_f64 * row as f64 / (rows - 1) as f64) as usize;
+                        if progress != old_progress {
+                            println!("Progress (loop {} of {}): {}%", i, num_files + 1, progress);
+                            old_progress = progress;
+                        }
+                    }
+                }
+            }
+            i += 1;
+        }
+
+        for row in 0..rows {
+            for col in 0..columns {
+                if n[(row, col)] > 0i16 {
+                    let count = n[(row, col)] as f64;
+                    match stat {
+                        Stat::Mean => {
+                            output[(row, col)] = mean[(row, col)];
+                        }
+                        Stat::Variance => {
+                            output[(row, col)] = if count > 1f64 {
+                                m2[(row, col)] / (count - 1f64)
+                            } else {
+                                0f64
+                            };
+                        }
+                        Stat::StdDev => {
+                            output[(row, col)] = if count > 1f64 {
+                                (m2[(row, col)] / (count - 1f64)).sqrt()
+                            } else {
+                                0f64
+                            };
+                        }
+                        Stat::Range => {
+                            output[(row, col)] = aux[(row, col)] - output[(row, col)];
+                        }
+                        Stat::Majority | Stat::Minority => {
+                            let idx = (row * columns + col) as usize;
+                            let want_max = stat == Stat::Majority;
+                            let mut best_val = out_nodata;
+                            let mut best_count = 0u32;
+                            let mut seen = false;
+                            for (bits, c) in &counts[idx] {
+                                let val = f64::from_bits(*bits as u64);
+                                // HashMap iteration order is not stable, so ties on count are
+                                // broken deterministically by preferring the lowest value.
+                                let better = if !seen {
+                                    true
+                                } else if *c != best_count {
+                                    if want_max { *c > best_count } else { *c < best_count }
+                                } else {
+                                    val < best_val
+                                };
+                                if better {
+                                    best_val = val;
+                                    best_count = *c;
+                                    seen = true;
+                                }
+                            }
+                            output[(row, col)] = best_val;
+                        }
+                        // Min, Max and Sum already hold their final value in output.
+                        Stat::Min | Stat::Max | Stat::Sum => {}
+                    }
+                } else {
+                    output[(row, col)] = out_nodata;
+                }
+            }
+            if verbose {
+                progress = (100.0_f64 * row as f64 / (rows - 1) as f64) as usize;
+                if progress != old_progress {
+                    println!("Progress (loop {} of {}): {}%", num_files + 1, num_files + 1, progress);
+                    old_progress = progress;
+                }
+            }
+        }
+
+        let end = time::now();
+        let elapsed_time = end - start;
+        output.add_metadata_entry(format!("Created by whitebox_tools\' {} tool", self.get_tool_name()));
+        output.add_metadata_entry(format!("Elapsed Time (including I/O): {}", elapsed_time).replace("PT", ""));
+
+        if verbose { println!("Saving data...") };
+        let _ = match output.write() {
+            Ok(_) => if verbose { println!("Output file written") },
+            Err(e) => return Err(e),
+        };
+
+        println!("{}", &format!("Elapsed Time (including I/O): {}", elapsed_time).replace("PT", ""));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+/*
+This file is part of the WhiteboxTools geospatial analysis library.
+Authors: Dr. John Lindsay
+Created: July 24 2017
+Last Modified: July 24, 2017
+License: MIT
+*/
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+
+// Global flags consumed by the command-line front-end before a tool's `run` is invoked.
+// They are silently ignored by the parser so that tools need not declare them.
+const RESERVED_FLAGS: [&'static str; 8] =
+    ["-r", "--run", "--wd", "-v", "--verbose", "-h", "--help", "--toolbox"];
+
+// The value type a parameter accepts, used both for validation and for the
+// auto-generated help text.
+#[derive(Clone, PartialEq)]
+pub enum ParameterType {
+    Path,
+    Float,
+    // An enumerated value restricted to the listed (lower-case) options.
+    Enum(Vec<String>),
+    // A comma- or semicolon-separated list, e.g. a group of input files.
+    List,
+    // A valueless flag; its mere presence sets the parameter to "true".
+    Boolean,
+}
+
+// A single declarative parameter: its canonical name, the flags (aliases) that set it,
+// a one-line description, its value type, and whether it is required or has a default.
+pub struct ToolParameter {
+    pub name: String,
+    pub flags: Vec<String>,
+    pub description: String,
+    pub parameter_type: ParameterType,
+    pub default: Option<String>,
+    pub optional: bool,
+}
+
+// The full parameter schema for a tool. The text returned by `get_tool_parameters` is
+// derived from this; the schema does not produce the `get_example_usage` string.
+pub struct ParameterSchema {
+    parameters: Vec<ToolParameter>,
+}
+
+impl ParameterSchema {
+    pub fn new() -> ParameterSchema {
+        ParameterSchema { parameters: vec![] }
+    }
+
+    // Appends a parameter declaration, returning `self` so declarations can be chained.
+    pub fn add(mut self, param: ToolParameter) -> ParameterSchema {
+        self.parameters.push(param);
+        self
+    }
+
+    // Resolves a raw flag (e.g. "-i" or "--inputs") to the parameter it sets.
+    fn find(&self, flag: &str) -> Option<&ToolParameter> {
+        let flag = flag.to_lowercase();
+        self.parameters.iter().find(|p| p.flags.iter().any(|f| f.to_lowercase() == flag))
+    }
+
+    // Consumes `args` against the schema, producing a typed parameter map. Returns a
+    // clear error for an unknown flag, a missing value, or a value that fails its type's
+    // validation; fills in declared defaults and verifies that all required flags are set.
+    pub fn parse(&self, args: &[String]) -> Result<ParsedParameters, Error> {
+        let mut values: HashMap<String, String> = HashMap::new();
+        let mut i = 0;
+        while i < args.len() {
+            let raw = args[i].replace("\"", "").replace("\'", "");
+            let (flag, inline) = match raw.find('=') {
+                Some(p) => (raw[..p].to_string(), Some(raw[(p + 1)..].to_string())),
+                None => (raw.clone(), None),
+            };
+
+            match self.find(&flag) {
+                Some(param) => {
+                    if param.parameter_type == ParameterType::Boolean {
+                        values.insert(param.name.clone(), "true".to_string());
+                    } else {
+                        let val = match inline {
+                            Some(v) => v,
+                            None => {
+                                // The value is the next argument; a trailing flag with no
+                                // value would otherwise panic in the old manual parsers.
+                                if i + 1 >= args.len() {
+                                    return Err(Error::new(ErrorKind::InvalidInput,
+                                        format!("The '{}' flag requires a value but none was provided.", flag)));
+                                }
+                                i += 1;
+                                args[i].replace("\"", "").replace("\'", "")
+                            }
+                        };
+                        validate(&param.parameter_type, &flag, &val)?;
+                        values.insert(param.name.clone(), val);
+                    }
+                }
+                None => {
+                    if flag.starts_with('-') && !RESERVED_FLAGS.contains(&flag.to_lowercase().as_ref()) {
+                        return Err(Error::new(ErrorKind::InvalidInput,
+                            format!("Unknown flag '{}'. Please see help (-h) for parameter descriptions.", flag)));
+                    }
+                }
+            }
+            i += 1;
+        }
+
+        for param in &self.parameters {
+            if !values.contains_key(&param.name) {
+                match param.default {
+                    Some(ref d) => { values.insert(param.name.clone(), d.clone()); }
+                    None => {
+                        if !param.optional {
+                            return Err(Error::new(ErrorKind::InvalidInput,
+                                format!("The required '{}' parameter was not supplied.", param.flags[0])));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(ParsedParameters { values: values })
+    }
+
+    // The multi-line description used by `get_tool_parameters`.
+    pub fn to_parameters_string(&self) -> String {
+        let mut s = String::new();
+        for param in &self.parameters {
+            s.push_str(&format!("{:<16} {}\n", param.flags.join(", "), param.description));
+        }
+        s
+    }
+}
+
+// Validates a raw value against its declared type.
+fn validate(parameter_type: &ParameterType, flag: &str, val: &str) -> Result<(), Error> {
+    match *parameter_type {
+        ParameterType::Float => {
+            val.parse::<f64>().map(|_| ()).map_err(|_| Error::new(ErrorKind::InvalidInput,
+                format!("The value of '{}' must be a number.", flag)))
+        }
+        ParameterType::Enum(ref options) => {
+            if options.iter().any(|o| o == &val.to_lowercase()) {
+                Ok(())
+            } else {
+                Err(Error::new(ErrorKind::InvalidInput,
+                    format!("The value of '{}' must be one of: {}.", flag, options.join(", "))))
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+// The typed result of parsing, keyed by each parameter's canonical name.
+pub struct ParsedParameters {
+    values: HashMap<String, String>,
+}
+
+impl ParsedParameters {
+    // The raw string value of a parameter, if it was supplied or has a default.
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.values.get(name).cloned()
+    }
+
+    // The value of a parameter (its declared default when it was not supplied), or an
+    // empty string when it was neither supplied nor has a default.
+    pub fn value_or_default(&self, name: &str) -> String {
+        self.values.get(name).cloned().unwrap_or_default()
+    }
+
+    // A parameter parsed as a float. Returns `None` when it was neither supplied nor has
+    // a default; a supplied value is guaranteed parseable because `parse` validated it.
+    pub fn get_float(&self, name: &str) -> Option<f64> {
+        self.values.get(name).map(|v| v.parse::<f64>().unwrap())
+    }
+
+    // Whether a boolean flag was present.
+    pub fn is_set(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    // A list parameter split on commas or semicolons, with empty entries dropped.
+    pub fn get_list(&self, name: &str) -> Vec<String> {
+        match self.values.get(name) {
+            Some(v) => {
+                let sep = if v.contains(';') { ';' } else { ',' };
+                v.split(sep).map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+            }
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A representative schema exercising each value type the parser supports.
+    fn schema() -> ParameterSchema {
+        ParameterSchema::new()
+            .add(ToolParameter {
+                name: "inputs".to_string(),
+                flags: vec!["-i".to_string(), "--inputs".to_string()],
+                description: "Input rasters.".to_string(),
+                parameter_type: ParameterType::List,
+                default: None,
+                optional: false,
+            })
+            .add(ToolParameter {
+                name: "output".to_string(),
+                flags: vec!["-o".to_string(), "--output".to_string()],
+                description: "Output raster.".to_string(),
+                parameter_type: ParameterType::Path,
+                default: None,
+                optional: false,
+            })
+            .add(ToolParameter {
+                name: "method".to_string(),
+                flags: vec!["--method".to_string()],
+                description: "Resampling kernel.".to_string(),
+                parameter_type: ParameterType::Enum(vec!["nn".to_string(), "bilinear".to_string()]),
+                default: Some("bilinear".to_string()),
+                optional: true,
+            })
+            .add(ToolParameter {
+                name: "percentile".to_string(),
+                flags: vec!["--percentile".to_string()],
+                description: "Quantile.".to_string(),
+                parameter_type: ParameterType::Float,
+                default: None,
+                optional: true,
+            })
+    }
+
+    fn args(a: &[&str]) -> Vec<String> {
+        a.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn accepts_both_keyval_and_spaced_forms() {
+        let p = schema().parse(&args(&["--inputs=a.dep,b.dep", "-o", "out.dep"])).unwrap();
+        assert_eq!(p.value_or_default("output"), "out.dep");
+        assert_eq!(p.get_list("inputs"), vec!["a.dep".to_string(), "b.dep".to_string()]);
+    }
+
+    #[test]
+    fn trailing_flag_without_value_errors() {
+        // The specific out-of-bounds panic this parser set out to fix.
+        assert!(schema().parse(&args(&["-i", "a.dep,b.dep", "-o"])).is_err());
+    }
+
+    #[test]
+    fn unknown_flag_is_rejected_but_reserved_flag_passes_through() {
+        assert!(schema().parse(&args(&["-i", "a.dep", "-o", "out.dep", "--bogus", "x"])).is_err());
+        // Reserved front-end flags are silently ignored rather than rejected.
+        assert!(schema().parse(&args(&["-i", "a.dep", "-o", "out.dep", "-v"])).is_ok());
+    }
+
+    #[test]
+    fn enum_and_float_values_are_validated() {
+        assert!(schema().parse(&args(&["-i", "a.dep", "-o", "out.dep", "--method=cubic"])).is_err());
+        assert!(schema().parse(&args(&["-i", "a.dep", "-o", "out.dep", "--percentile=foo"])).is_err());
+        let p = schema().parse(&args(&["-i", "a.dep", "-o", "out.dep", "--method=nn", "--percentile=90"])).unwrap();
+        assert_eq!(p.value_or_default("method"), "nn");
+        assert_eq!(p.get_float("percentile"), Some(90f64));
+    }
+
+    #[test]
+    fn missing_required_errors_and_defaults_fill_in() {
+        assert!(schema().parse(&args(&["-o", "out.dep"])).is_err());
+        let p = schema().parse(&args(&["-i", "a.dep", "-o", "out.dep"])).unwrap();
+        assert_eq!(p.value_or_default("method"), "bilinear");
+    }
+
+    #[test]
+    fn list_splits_on_comma_or_semicolon() {
+        let p = schema().parse(&args(&["-i", "a.dep; b.dep ;c.dep", "-o", "out.dep"])).unwrap();
+        assert_eq!(p.get_list("inputs"), vec!["a.dep".to_string(), "b.dep".to_string(), "c.dep".to_string()]);
+    }
+}